@@ -0,0 +1,126 @@
+//! Drivers built on the blocking `spi::Write<u8>` trait.
+//!
+//! Some HALs (notably esp-idf-hal) only expose a blocking SPI `Write`/`SpiBus`
+//! interface and never implement `FullDuplex`, which the [`crate`] root drivers
+//! require. The drivers here target that family of HALs instead.
+//!
+//! Rather than pumping one pattern byte at a time through `send`/`read`, a whole
+//! frame is expanded into a caller supplied scratch buffer and handed to the bus
+//! with `spi.write(&buf)`. This avoids the matched-read hack entirely. The
+//! buffer only needs to hold a single chunk: if a frame is larger than the
+//! buffer it is rendered and written in buffer sized pieces.
+
+use core::marker::PhantomData;
+
+use crate::device::{self, Device, EncodeColor};
+use crate::Timing;
+
+use hal::blocking::spi::Write;
+
+use smart_leds_trait::SmartLedsWrite;
+
+/// Render `bytes` into `buffer`, flushing full chunks to `spi`, then emit the
+/// trailing reset zeros.
+fn write_frame<SPI, E, I>(
+    spi: &mut SPI,
+    timing: &Timing,
+    reset_bytes: usize,
+    buffer: &mut [u8],
+    bytes: I,
+) -> Result<(), E>
+where
+    SPI: Write<u8, Error = E>,
+    I: Iterator<Item = u8>,
+{
+    let width = timing.bytes_per_byte();
+    let mut cursor = 0;
+    for data in bytes {
+        if cursor + width > buffer.len() {
+            spi.write(&buffer[..cursor])?;
+            cursor = 0;
+        }
+        timing.encode_into(data, &mut buffer[cursor..]);
+        cursor += width;
+    }
+    if cursor > 0 {
+        spi.write(&buffer[..cursor])?;
+    }
+
+    // Latch the frame by holding the line low, reusing the scratch buffer.
+    let mut remaining = reset_bytes;
+    for slot in buffer.iter_mut() {
+        *slot = 0;
+    }
+    while remaining > 0 {
+        let chunk = remaining.min(buffer.len());
+        spi.write(&buffer[..chunk])?;
+        remaining -= chunk;
+    }
+    Ok(())
+}
+
+/// Driver for strings of smart LEDs over a blocking SPI bus, parameterized over
+/// the [`Device`] describing the channel order.
+///
+/// The default device is [`device::Ws2812`]; [`Sk6812w`] is provided as a type
+/// alias for the GRBW variant.
+pub struct Ws2812<'a, SPI, DEV = device::Ws2812> {
+    spi: SPI,
+    timing: Timing,
+    reset_bytes: usize,
+    buffer: &'a mut [u8],
+    device: PhantomData<DEV>,
+}
+
+/// Driver for strings of SK6812-W LEDs over a blocking SPI bus.
+pub type Sk6812w<'a, SPI> = Ws2812<'a, SPI, device::Sk6812w>;
+
+impl<'a, SPI, DEV> Ws2812<'a, SPI, DEV>
+where
+    DEV: Device,
+{
+    /// Create a driver from the provided SPI peripheral, the [`Timing`]
+    /// describing its clock frequency, and a scratch buffer used to render the
+    /// pattern before it is written to the bus.
+    ///
+    /// The buffer must be at least `bytes_per_byte` (3 or 4) bytes long so a
+    /// single data byte can be rendered into it.
+    pub fn new(spi: SPI, timing: Timing, buffer: &'a mut [u8]) -> Self {
+        assert!(
+            buffer.len() >= timing.bytes_per_byte(),
+            "render buffer too small for one data byte"
+        );
+        let reset_bytes = timing.reset_bytes(DEV::RESET_NS);
+        Self {
+            spi,
+            timing,
+            reset_bytes,
+            buffer,
+            device: PhantomData,
+        }
+    }
+}
+
+impl<'a, SPI, E, DEV> SmartLedsWrite for Ws2812<'a, SPI, DEV>
+where
+    SPI: Write<u8, Error = E>,
+    DEV: Device,
+{
+    type Error = E;
+    type Color = DEV::Color;
+    /// Write all the items of an iterator to a ws2812 strip
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), E>
+    where
+        T: Iterator<Item = I>,
+        I: Into<Self::Color>,
+    {
+        let bytes = iterator.flat_map(|item| item.into().encode());
+        write_frame(
+            &mut self.spi,
+            &self.timing,
+            self.reset_bytes,
+            self.buffer,
+            bytes,
+        )
+    }
+}
@@ -0,0 +1,60 @@
+//! Device abstraction describing how a color maps to the bytes a strip expects.
+//!
+//! The wire protocol is the same for the whole WS2812 family; strips differ
+//! only in which color type they accept, the order the channels are clocked
+//! out in, and how long the line must be held low to latch a frame. A
+//! [`Device`] captures those differences so a single driver can serve
+//! all of them, and so users can describe exotic strips by implementing
+//! [`Device`] and [`EncodeColor`] for their own color types.
+
+use smart_leds_trait::{RGB8, RGBW};
+
+/// Marker trait for a specific kind of addressable LED strip.
+pub trait Device {
+    /// The color type accepted by strips of this device.
+    type Color: EncodeColor;
+
+    /// Minimum time the line must be held low to latch a frame, in nanoseconds.
+    const RESET_NS: u32;
+}
+
+/// Expand a color into the data bytes a strip expects, in channel order.
+pub trait EncodeColor {
+    /// Iterator of data bytes produced by [`encode`](EncodeColor::encode).
+    type Bytes: IntoIterator<Item = u8>;
+
+    /// Expand `self` into its per-channel data bytes.
+    fn encode(self) -> Self::Bytes;
+}
+
+impl EncodeColor for RGB8 {
+    type Bytes = [u8; 3];
+
+    fn encode(self) -> [u8; 3] {
+        [self.g, self.r, self.b]
+    }
+}
+
+impl EncodeColor for RGBW<u8, u8> {
+    type Bytes = [u8; 4];
+
+    fn encode(self) -> [u8; 4] {
+        [self.g, self.r, self.b, self.a.0]
+    }
+}
+
+/// Standard WS2812 strip: GRB ordered [`RGB8`] pixels.
+pub struct Ws2812;
+
+impl Device for Ws2812 {
+    type Color = RGB8;
+    const RESET_NS: u32 = 50_000;
+}
+
+/// SK6812-W strip: GRBW ordered [`RGBW`] pixels.
+pub struct Sk6812w;
+
+impl Device for Sk6812w {
+    type Color = RGBW<u8, u8>;
+    const RESET_NS: u32 = 80_000;
+}
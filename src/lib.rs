@@ -12,11 +12,17 @@
 
 extern crate embedded_hal as hal;
 
+pub mod blocking;
+pub mod device;
 pub mod prerendered;
 
+use core::marker::PhantomData;
+
 use hal::spi::{FullDuplex, Mode, Phase, Polarity};
 
-use smart_leds_trait::{SmartLedsWrite, RGB8, RGBW};
+use smart_leds_trait::SmartLedsWrite;
+
+use device::{Device, EncodeColor};
 
 use nb;
 use nb::block;
@@ -30,54 +36,140 @@ pub const MODE: Mode = Mode {
     phase: Phase::CaptureOnFirstTransition,
 };
 
+/// Bit encoding parameters derived from the actual SPI clock frequency.
+///
+/// The WS2812 protocol encodes each data bit as a pulse whose high time tells a
+/// one apart from a zero, with a total bit period of roughly 1.25 µs. We
+/// approximate that waveform by emitting several SPI bits per data bit; how many
+/// depends on the SPI clock, so that one data period lands near 1.25 µs.
+///
+/// Construct one with [`Timing::from_hz`] from the frequency the SPI peripheral
+/// is actually clocked at. This keeps the driver working across roughly 2–8 MHz
+/// instead of only the nominal 3 MHz (WS2812) / 4 MHz (SK6812w).
+#[derive(Clone, Copy)]
+pub struct Timing {
+    /// Number of SPI bits emitted per data bit (3 or 4).
+    bits_per_bit: u8,
+    /// SPI bit pattern for a data one, right aligned in `bits_per_bit` bits.
+    one: u8,
+    /// SPI bit pattern for a data zero, right aligned in `bits_per_bit` bits.
+    zero: u8,
+    /// The SPI clock frequency in Hz.
+    freq: u32,
+}
+
+impl Timing {
+    /// Choose an encoding for the given SPI clock frequency in Hz.
+    ///
+    /// For frequencies up to ~3.8 MHz three SPI bits encode one data bit
+    /// (`0b110`/`0b100`); from ~4.6 MHz four bits are used (`0b1100`/`0b1000`),
+    /// keeping the high time near 0.8 µs for a one and 0.4 µs for a zero.
+    ///
+    /// Only clocks in roughly 2–8 MHz are supported: the 3-bit encoding holds
+    /// its timing budget down to ~2 MHz and the 4-bit encoding up to ~8 MHz.
+    /// Frequencies outside that range are still accepted but will produce pulses
+    /// too long or too short for the LEDs to latch reliably.
+    pub fn from_hz(freq: u32) -> Self {
+        if freq >= 4_600_000 {
+            Self {
+                bits_per_bit: 4,
+                one: 0b1100,
+                zero: 0b1000,
+                freq,
+            }
+        } else {
+            Self {
+                bits_per_bit: 3,
+                one: 0b110,
+                zero: 0b100,
+                freq,
+            }
+        }
+    }
+
+    /// Number of trailing zero bytes needed to hold the line low for at least
+    /// `t_reset_ns`, i.e. `ceil(freq * t_reset / 8)`.
+    fn reset_bytes(&self, t_reset_ns: u32) -> usize {
+        let bits = (self.freq as u64 * t_reset_ns as u64).div_ceil(1_000_000_000);
+        bits.div_ceil(8) as usize
+    }
+
+    /// Number of SPI bytes one data byte expands to (3 or 4).
+    pub(crate) fn bytes_per_byte(&self) -> usize {
+        self.bits_per_bit as usize
+    }
+
+    /// Expand one data byte into `bytes_per_byte()` SPI pattern bytes, writing
+    /// them into the start of `out`. `8 * bits_per_bit` is always a multiple of
+    /// 8, so the expansion is exactly byte aligned.
+    pub(crate) fn encode_into(&self, mut data: u8, out: &mut [u8]) {
+        let n = self.bits_per_bit as u32;
+        let mut acc: u64 = 0;
+        for _ in 0..8 {
+            let pattern = if data & 0x80 != 0 { self.one } else { self.zero };
+            acc = (acc << n) | pattern as u64;
+            data <<= 1;
+        }
+        let total = 8 * n as usize;
+        for (i, slot) in out.iter_mut().take(self.bytes_per_byte()).enumerate() {
+            *slot = (acc >> (total - 8 * (i + 1))) as u8;
+        }
+    }
+}
+
 /// The internal communication layer implementation.
 struct CommLayer<SPI> {
     spi: SPI,
+    timing: Timing,
+    reset_bytes: usize,
 }
 
 impl<SPI, E> CommLayer<SPI>
 where
     SPI: FullDuplex<u8, Error = E>,
 {
-    /// The SPI bus should run with 3 Mhz, otherwise this won't work.
-    ///
-    /// You may need to look at the datasheet and your own hal to verify this.
+    /// Build the communication layer over `spi`, using `timing` to encode data
+    /// bits and emitting `reset_bytes` trailing zero bytes on [`flush`].
     ///
     /// Please ensure that the mcu is pretty fast, otherwise weird timing
     /// issues will occur
-    pub fn new(spi: SPI) -> Self {
-        Self { spi }
+    pub fn new(spi: SPI, timing: Timing, reset_bytes: usize) -> Self {
+        Self {
+            spi,
+            timing,
+            reset_bytes,
+        }
     }
 
     /// Write a single byte for ws2812 devices
     fn write_byte(&mut self, mut data: u8) -> Result<(), E> {
-        let mut serial_bits: u32 = 0;
-        for _ in 0..3 {
-            let bit = data & 0x80;
-            let pattern = if bit == 0x80 { 0b110 } else { 0b100 };
-            serial_bits = pattern | (serial_bits << 3);
-            data <<= 1;
-        }
-        block!(self.spi.send((serial_bits >> 1) as u8))?;
-        // Split this up to have a bit more lenient timing
-        for _ in 3..8 {
-            let bit = data & 0x80;
-            let pattern = if bit == 0x80 { 0b110 } else { 0b100 };
-            serial_bits = pattern | (serial_bits << 3);
+        let n = self.timing.bits_per_bit;
+        // 8 data bits times 3 or 4 pattern bits is always byte aligned, so the
+        // accumulator drains completely for every input byte.
+        let mut acc: u32 = 0;
+        let mut nbits: u32 = 0;
+        for _ in 0..8 {
+            let pattern = if data & 0x80 != 0 {
+                self.timing.one
+            } else {
+                self.timing.zero
+            };
+            acc = (acc << n) | pattern as u32;
+            nbits += n as u32;
             data <<= 1;
+            while nbits >= 8 {
+                nbits -= 8;
+                block!(self.spi.send((acc >> nbits) as u8))?;
+                // Some implementations (stm32f0xx-hal) want a matching read
+                // We don't want to block so we just hope it's ok this way
+                self.spi.read().ok();
+            }
         }
-        // Some implementations (stm32f0xx-hal) want a matching read
-        // We don't want to block so we just hope it's ok this way
-        self.spi.read().ok();
-        block!(self.spi.send((serial_bits >> 8) as u8))?;
-        self.spi.read().ok();
-        block!(self.spi.send(serial_bits as u8))?;
-        self.spi.read().ok();
         Ok(())
     }
 
     fn flush(&mut self) -> Result<(), E> {
-        for _ in 0..20 {
+        for _ in 0..self.reset_bytes {
             block!(self.spi.send(0))?;
             self.spi.read().ok();
         }
@@ -85,50 +177,45 @@ where
     }
 }
 
-/// Driver for strings of Ws2812 LEDs. This driver expects the SPI bus to be
-/// running at ~3MHz.
-pub struct Ws2812<SPI> {
+/// Driver for strings of smart LEDs, parameterized over the [`Device`] that
+/// describes how a color maps to output bytes.
+///
+/// The default device is [`device::Ws2812`] (GRB ordered [`RGB8`] pixels);
+/// [`Sk6812w`] is provided as a type alias for the GRBW variant. Implement your
+/// own [`Device`] to drive strips with other channel orderings.
+///
+/// [`RGB8`]: smart_leds_trait::RGB8
+pub struct Ws2812<SPI, DEV = device::Ws2812> {
     comms: CommLayer<SPI>,
+    device: PhantomData<DEV>,
 }
 
-impl<SPI, E> Ws2812<SPI>
+impl<SPI, E, DEV> Ws2812<SPI, DEV>
 where
     SPI: FullDuplex<u8, Error = E>,
+    DEV: Device,
 {
-    /// Create a smart led strip driver from the provided SPI peripheral. The
-    /// peripheral should be running at 3 MHz.
-    pub fn new(spi: SPI) -> Self {
+    /// Create a smart led strip driver from the provided SPI peripheral and the
+    /// [`Timing`] describing its actual clock frequency.
+    pub fn new(spi: SPI, timing: Timing) -> Self {
+        let reset_bytes = timing.reset_bytes(DEV::RESET_NS);
         Self {
-            comms: CommLayer::new(spi),
+            comms: CommLayer::new(spi, timing, reset_bytes),
+            device: PhantomData,
         }
     }
 }
 
-/// Driver for strings of SK6812-W LEDs. This driver expects the SPI bus to be
-/// running at ~4MHz.
-pub struct Sk6812w<SPI> {
-    comms: CommLayer<SPI>,
-}
-
-impl<SPI, E> Sk6812w<SPI>
-where
-    SPI: FullDuplex<u8, Error = E>,
-{
-    /// Create a smart led strip driver from the provided SPI peripheral. The
-    /// peripheral should be running at 4 MHz.
-    pub fn new(spi: SPI) -> Self {
-        Self {
-            comms: CommLayer::new(spi),
-        }
-    }
-}
+/// Driver for strings of SK6812-W LEDs.
+pub type Sk6812w<SPI> = Ws2812<SPI, device::Sk6812w>;
 
-impl<SPI, E> SmartLedsWrite for Sk6812w<SPI>
+impl<SPI, E, DEV> SmartLedsWrite for Ws2812<SPI, DEV>
 where
     SPI: FullDuplex<u8, Error = E>,
+    DEV: Device,
 {
     type Error = E;
-    type Color = RGBW<u8, u8>;
+    type Color = DEV::Color;
     /// Write all the items of an iterator to a ws2812 strip
     fn write<T, I>(&mut self, iterator: T) -> Result<(), E>
     where
@@ -141,39 +228,85 @@ where
 
         for item in iterator {
             let item = item.into();
-            self.comms.write_byte(item.g)?;
-            self.comms.write_byte(item.r)?;
-            self.comms.write_byte(item.b)?;
-            self.comms.write_byte(item.a.0)?;
+            for byte in item.encode() {
+                self.comms.write_byte(byte)?;
+            }
         }
         self.comms.flush()?;
         Ok(())
     }
 }
 
-impl<SPI, E> SmartLedsWrite for Ws2812<SPI>
-where
-    SPI: FullDuplex<u8, Error = E>,
-{
-    type Error = E;
-    type Color = RGB8;
-    /// Write all the items of an iterator to a ws2812 strip
-    fn write<T, I>(&mut self, iterator: T) -> Result<(), E>
-    where
-        T: Iterator<Item = I>,
-        I: Into<Self::Color>,
-    {
-        if cfg!(feature = "mosi_idle_high") {
-            self.comms.flush()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Minimal `FullDuplex` stub that records everything handed to `send`.
+    struct MockSpi {
+        out: [u8; 64],
+        len: usize,
+    }
+
+    impl MockSpi {
+        fn new() -> Self {
+            Self {
+                out: [0; 64],
+                len: 0,
+            }
         }
+    }
 
-        for item in iterator {
-            let item = item.into();
-            self.comms.write_byte(item.g)?;
-            self.comms.write_byte(item.r)?;
-            self.comms.write_byte(item.b)?;
+    impl FullDuplex<u8> for MockSpi {
+        type Error = ();
+
+        fn send(&mut self, word: u8) -> nb::Result<(), ()> {
+            self.out[self.len] = word;
+            self.len += 1;
+            Ok(())
         }
-        self.comms.flush()?;
-        Ok(())
+
+        fn read(&mut self) -> nb::Result<u8, ()> {
+            Ok(0)
+        }
+    }
+
+    /// Bytes that `write_byte` actually puts on the wire for `data`.
+    fn sent(timing: Timing, data: u8) -> ([u8; 4], usize) {
+        let mut comms = CommLayer::new(MockSpi::new(), timing, 0);
+        comms.write_byte(data).unwrap();
+        let mut out = [0u8; 4];
+        out[..comms.spi.len].copy_from_slice(&comms.spi.out[..comms.spi.len]);
+        (out, comms.spi.len)
+    }
+
+    #[test]
+    fn from_hz_selects_encoding_at_edges() {
+        assert_eq!(Timing::from_hz(2_000_000).bits_per_bit, 3);
+        assert_eq!(Timing::from_hz(3_800_000).bits_per_bit, 3);
+        assert_eq!(Timing::from_hz(4_600_000).bits_per_bit, 4);
+        assert_eq!(Timing::from_hz(8_000_000).bits_per_bit, 4);
+    }
+
+    #[test]
+    fn encode_into_matches_write_byte() {
+        for &freq in &[3_000_000u32, 5_000_000] {
+            let timing = Timing::from_hz(freq);
+            let width = timing.bytes_per_byte();
+            for &data in &[0x00u8, 0x01, 0x80, 0xAA, 0xFF] {
+                let mut buf = [0u8; 4];
+                timing.encode_into(data, &mut buf);
+                let (sent, len) = sent(timing, data);
+                assert_eq!(len, width);
+                assert_eq!(buf[..width], sent[..width]);
+            }
+        }
+    }
+
+    #[test]
+    fn reset_bytes_is_ceil() {
+        // 3 MHz, 50 µs -> 150 bit periods -> ceil(150 / 8) = 19 bytes.
+        assert_eq!(Timing::from_hz(3_000_000).reset_bytes(50_000), 19);
+        // 4 MHz, 80 µs -> 320 bit periods -> ceil(320 / 8) = 40 bytes.
+        assert_eq!(Timing::from_hz(4_000_000).reset_bytes(80_000), 40);
     }
 }
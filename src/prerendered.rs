@@ -0,0 +1,151 @@
+//! Prerendered output for DMA driven SPI.
+//!
+//! The root drivers expand the pattern one byte at a time and block the CPU on
+//! each `send`. For long strips that busy-wait can run into milliseconds, which
+//! is awkward when the application also wants to run animations or other
+//! peripherals. This module instead expands a whole frame into a caller supplied
+//! buffer up front and then hands that buffer to the SPI bus in one shot.
+//!
+//! [`Ws2812::render`] fills the buffer with the expanded pattern plus the
+//! trailing reset bytes and returns the exact slice written, so the caller can
+//! feed it straight into a DMA transfer. [`Ws2812::write_dma`] wraps that
+//! pattern: it renders the frame and then invokes a caller provided closure that
+//! starts a DMA transfer of the rendered slice — mirroring the RP2040 approach
+//! of driving SPI TX from DMA — returning the transfer handle so the caller can
+//! poll or wait on it.
+//!
+//! A transfer handle that holds the rendered slice borrows the driver (and
+//! therefore the buffer) for as long as it lives, so the borrow checker prevents
+//! the buffer from being re-rendered while such a transfer is still reading it.
+
+use core::marker::PhantomData;
+
+use crate::device::{self, Device, EncodeColor};
+use crate::Timing;
+
+use hal::blocking::spi::Write;
+
+use smart_leds_trait::SmartLedsWrite;
+
+/// Prerendering driver for strings of smart LEDs, parameterized over the
+/// [`Device`] describing the channel order.
+///
+/// The default device is [`device::Ws2812`]; [`Sk6812w`] is provided as a type
+/// alias for the GRBW variant.
+pub struct Ws2812<'a, SPI, DEV = device::Ws2812> {
+    spi: SPI,
+    timing: Timing,
+    reset_bytes: usize,
+    buffer: &'a mut [u8],
+    device: PhantomData<DEV>,
+}
+
+/// Prerendering driver for strings of SK6812-W LEDs.
+pub type Sk6812w<'a, SPI> = Ws2812<'a, SPI, device::Sk6812w>;
+
+impl<'a, SPI, DEV> Ws2812<'a, SPI, DEV>
+where
+    DEV: Device,
+{
+    /// Create a prerendering driver from the provided SPI peripheral, the
+    /// [`Timing`] describing its clock frequency, and a scratch buffer large
+    /// enough to hold one rendered frame plus its reset bytes.
+    pub fn new(spi: SPI, timing: Timing, buffer: &'a mut [u8]) -> Self {
+        let reset_bytes = timing.reset_bytes(DEV::RESET_NS);
+        Self {
+            spi,
+            timing,
+            reset_bytes,
+            buffer,
+            device: PhantomData,
+        }
+    }
+
+    /// Render `iterator` into the internal buffer, returning the number of bytes
+    /// written (expanded pattern followed by the trailing reset zeros). If the
+    /// buffer is too small this panics in debug builds; in release builds the
+    /// bytes that would not fit are dropped.
+    fn render_internal<T, I>(&mut self, iterator: T) -> usize
+    where
+        T: Iterator<Item = I>,
+        I: Into<DEV::Color>,
+    {
+        let width = self.timing.bytes_per_byte();
+        let mut cursor = 0;
+        for item in iterator {
+            for byte in item.into().encode() {
+                debug_assert!(
+                    cursor + width <= self.buffer.len(),
+                    "render buffer too small for frame"
+                );
+                if cursor + width <= self.buffer.len() {
+                    self.timing.encode_into(byte, &mut self.buffer[cursor..]);
+                    cursor += width;
+                }
+            }
+        }
+        debug_assert!(
+            cursor + self.reset_bytes <= self.buffer.len(),
+            "render buffer too small for reset bytes"
+        );
+        let end = (cursor + self.reset_bytes).min(self.buffer.len());
+        for slot in &mut self.buffer[cursor..end] {
+            *slot = 0;
+        }
+        end
+    }
+
+    /// Render a full frame into the internal buffer and return the exact slice
+    /// written: the expanded pattern bytes followed by the trailing reset bytes.
+    ///
+    /// The returned slice can be handed directly to a DMA transfer.
+    pub fn render<T, I>(&mut self, iterator: T) -> &[u8]
+    where
+        T: Iterator<Item = I>,
+        I: Into<DEV::Color>,
+    {
+        let len = self.render_internal(iterator);
+        &self.buffer[..len]
+    }
+
+    /// Render a full frame and hand the rendered slice to `start`, which is
+    /// expected to kick off a DMA transfer of those bytes out of `spi` and
+    /// return a handle the caller can poll or wait on.
+    ///
+    /// Both the SPI peripheral and the rendered slice are lent to `start` for
+    /// the lifetime `'t` of the `&mut self` borrow, and the returned handle is
+    /// bound to that same lifetime (`H: 't`). A handle that keeps the slice —
+    /// as a real DMA transfer must — therefore keeps `self` borrowed for as long
+    /// as it lives, so the borrow checker rejects any `render`/`write_dma` call
+    /// that would overwrite the buffer before the transfer is dropped or waited
+    /// on.
+    pub fn write_dma<'t, T, I, F, H>(&'t mut self, iterator: T, start: F) -> H
+    where
+        T: Iterator<Item = I>,
+        I: Into<DEV::Color>,
+        F: FnOnce(&'t mut SPI, &'t [u8]) -> H,
+        H: 't,
+    {
+        let len = self.render_internal(iterator);
+        start(&mut self.spi, &self.buffer[..len])
+    }
+}
+
+impl<'a, SPI, E, DEV> SmartLedsWrite for Ws2812<'a, SPI, DEV>
+where
+    SPI: Write<u8, Error = E>,
+    DEV: Device,
+{
+    type Error = E;
+    type Color = DEV::Color;
+    /// Render the whole frame and write it to the strip in a single blocking
+    /// `spi.write` call.
+    fn write<T, I>(&mut self, iterator: T) -> Result<(), E>
+    where
+        T: Iterator<Item = I>,
+        I: Into<Self::Color>,
+    {
+        let len = self.render_internal(iterator);
+        self.spi.write(&self.buffer[..len])
+    }
+}